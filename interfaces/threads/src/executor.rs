@@ -0,0 +1,210 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Cooperative, single-stack task executor.
+//!
+//! `spawn_thread` requires a second native stack, which is unsound on WASM because LLVM assumes
+//! only one stack ever exists. Tasks driven by [`Executor`] never switch stacks: they are plain
+//! `Future`s that yield control at their `.await` points, so a single WASM stack is all that's
+//! ever needed.
+
+use alloc::{boxed::Box, collections::VecDeque, rc::Rc};
+use core::{
+    cell::{Cell, RefCell},
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+/// A task spawned onto an [`Executor`], together with its current state.
+struct Task {
+    /// The task's future. `None` after it has finished, to drop its captured state early.
+    future: RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    /// `true` once the future has resolved; [`Executor::run`] then skips this task entirely.
+    finished: Cell<bool>,
+    /// `true` while this task is sitting in `ready_queue`, to avoid queueing it twice.
+    ///
+    /// This is tracked separately from whether the task is currently being polled, because a
+    /// future can call `wake_by_ref` on its own waker synchronously from inside `poll` (this is
+    /// exactly what [`yield_now`] does). That wake must still result in the task being
+    /// re-enqueued once `poll` returns `Pending`, even though the task was never "suspended" in
+    /// between: it went straight from running to woken without a gap.
+    scheduled: Cell<bool>,
+    /// Queue shared with the [`Executor`] that owns this task, so that its waker can re-enqueue
+    /// it from anywhere.
+    ready_queue: Rc<RefCell<VecDeque<Rc<Task>>>>,
+}
+
+impl Task {
+    /// Re-enqueues this task onto its executor's ready queue, unless it's already queued or has
+    /// finished.
+    fn wake(self: &Rc<Self>) {
+        if self.finished.get() || self.scheduled.replace(true) {
+            return;
+        }
+        self.ready_queue.borrow_mut().push_back(self.clone());
+    }
+
+    /// Builds a [`Waker`] for this task.
+    ///
+    /// `Task` is `Rc`-based rather than `Arc`-based, since this executor is single-threaded and
+    /// has no need for atomic refcounting; that rules out `alloc::task::Wake`, whose methods are
+    /// defined over `Arc<Self>` specifically. A hand-rolled [`RawWaker`] is the usual way around
+    /// that for `Rc`-based executors.
+    fn waker(self: &Rc<Self>) -> Waker {
+        unsafe { Waker::from_raw(Task::raw_waker(self.clone())) }
+    }
+
+    fn raw_waker(task: Rc<Task>) -> RawWaker {
+        RawWaker::new(Rc::into_raw(task) as *const (), &VTABLE)
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |ptr| {
+        // Borrow the `Rc` rather than taking ownership of it, so that cloning the waker only
+        // bumps the refcount instead of consuming the original.
+        let task = ManuallyDrop::new(unsafe { Rc::from_raw(ptr as *const Task) });
+        Task::raw_waker((*task).clone())
+    },
+    |ptr| {
+        let task = unsafe { Rc::from_raw(ptr as *const Task) };
+        Task::wake(&task);
+    },
+    |ptr| {
+        let task = ManuallyDrop::new(unsafe { Rc::from_raw(ptr as *const Task) });
+        Task::wake(&task);
+    },
+    |ptr| drop(unsafe { Rc::from_raw(ptr as *const Task) }),
+);
+
+/// A single-threaded, cooperative executor.
+///
+/// Tasks are stored in an intrusive ready-queue: a woken task re-enqueues itself, rather than
+/// the executor having to scan every task it knows about.
+pub struct Executor {
+    ready_queue: Rc<RefCell<VecDeque<Rc<Task>>>>,
+}
+
+impl Executor {
+    /// Creates a new, empty executor.
+    pub fn new() -> Self {
+        Executor {
+            ready_queue: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Spawns `future` onto this executor. It is queued straight away, and will be polled for the
+    /// first time during the next [`Executor::run`].
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        let task = Rc::new(Task {
+            future: RefCell::new(Some(Box::pin(future))),
+            finished: Cell::new(false),
+            scheduled: Cell::new(true),
+            ready_queue: self.ready_queue.clone(),
+        });
+        self.ready_queue.borrow_mut().push_back(task);
+    }
+
+    /// Polls every task currently in the ready queue, including tasks spawned or re-woken while
+    /// this call is running, until the queue is empty.
+    ///
+    /// Returns once no task is immediately runnable any more. Tasks parked on a waker are left
+    /// untouched; call `run` again after an external event might have woken one of them.
+    pub fn run(&self) {
+        loop {
+            // Popped in its own statement so that the queue's borrow ends here: a task woken or
+            // spawned from within `poll` below needs to borrow the queue again.
+            let task = self.ready_queue.borrow_mut().pop_front();
+            let task = match task {
+                Some(task) => task,
+                None => break,
+            };
+            let mut slot = task.future.borrow_mut();
+            let mut future = match slot.take() {
+                Some(future) => future,
+                // Already finished (or being polled elsewhere, which can't happen with this
+                // single-threaded executor): nothing to do.
+                None => continue,
+            };
+
+            // Cleared before polling, not after, so that a synchronous `wake_by_ref` from
+            // within `poll` (as `yield_now` does) re-enqueues the task instead of being
+            // dropped on the floor because it still looked "currently running".
+            task.scheduled.set(false);
+
+            let waker = task.waker();
+            let mut cx = Context::from_waker(&waker);
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(()) => task.finished.set(true),
+                Poll::Pending => *slot = Some(future),
+            }
+        }
+    }
+
+    /// Runs `future` to completion on a fresh executor of its own, and returns its output.
+    ///
+    /// That executor is not reachable from `future`, so no other task can be spawned onto it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `future` is still pending once it is no longer immediately runnable.
+    pub fn block_on<T: 'static>(future: impl Future<Output = T> + 'static) -> T {
+        let output = Rc::new(RefCell::new(None));
+
+        let executor = Executor::new();
+        executor.spawn({
+            let output = output.clone();
+            async move {
+                *output.borrow_mut() = Some(future.await);
+            }
+        });
+
+        // This executor never sleeps: `run` returns as soon as the ready queue is empty, which
+        // is only once the root future is done if it awaits nothing but `yield_now`.
+        executor.run();
+
+        Rc::try_unwrap(output)
+            .unwrap_or_else(|_| unreachable!())
+            .into_inner()
+            .expect("root future still pending once no task was runnable any more")
+    }
+}
+
+/// Returns a future that resolves the next time it is polled, after giving other ready tasks a
+/// chance to run in between.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// Future returned by [`yield_now`].
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
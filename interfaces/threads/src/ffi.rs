@@ -0,0 +1,69 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! FFI bindings for the `threads` interface.
+
+use parity_scale_codec::{Decode, Encode};
+
+pub const INTERFACE: redshirt_syscalls::InterfaceHash = redshirt_syscalls::InterfaceHash::from_raw_hash([
+    0x4c, 0xc5, 0x00, 0xdb, 0x62, 0xed, 0xe7, 0x37, 0xf8, 0xf7, 0xa8, 0xc8, 0x3c, 0x02, 0xb5, 0xfc,
+    0x5c, 0xbc, 0xeb, 0xf2, 0x6b, 0xff, 0xc4, 0x8f, 0xb4, 0x9b, 0x45, 0x40, 0xff, 0xc6, 0x73, 0x06,
+]);
+
+/// Message destined to the `threads` interface.
+#[derive(Debug, Encode, Decode)]
+pub enum ThreadsMessage {
+    /// Creates a new thread. See [`crate::spawn_thread`].
+    New(ThreadNew),
+    /// Parks the calling thread until the word at an address changes, or it is woken up. See
+    /// [`crate::futex::wait_if_equal`].
+    WaitIfEqual(WaitIfEqual),
+    /// Wakes up threads parked on an address through [`ThreadsMessage::WaitIfEqual`]. See
+    /// [`crate::futex::wake`].
+    Wake(Wake),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ThreadNew {
+    /// Pointer to the function to start executing.
+    pub fn_ptr: u32,
+    /// Pointer to pass as parameter to the function.
+    pub user_data: u32,
+}
+
+/// See [`ThreadsMessage::WaitIfEqual`].
+#[derive(Debug, Encode, Decode)]
+pub struct WaitIfEqual {
+    /// Memory address of the 32-bits word to examine.
+    pub addr: u32,
+    /// Value that `addr` was expected to contain at the time this message was sent.
+    ///
+    /// [`crate::futex::wait_if_equal`] already checks this locally before emitting the message
+    /// (it has access to the calling program's own memory, unlike the handler on the other end
+    /// of this interface), so by the time a handler sees a `WaitIfEqual` it can park
+    /// unconditionally: the word is known to have still matched `expected` an instant ago.
+    pub expected: u32,
+    /// Maximum number of nanoseconds to wait for, or `None` to wait forever.
+    pub timeout: Option<u64>,
+}
+
+/// See [`ThreadsMessage::Wake`].
+#[derive(Debug, Encode, Decode)]
+pub struct Wake {
+    /// Memory address that waiters are parked on.
+    pub addr: u32,
+    /// Maximum number of waiters to release. Use `u32::max_value()` to release all of them.
+    pub count: u32,
+}
@@ -0,0 +1,80 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Futex-style wait/wake synchronization.
+//!
+//! This is the primitive the `threads` interface exposes for blocking on a shared value instead
+//! of busy-polling it. Mutexes, condvars, and channels can be built on top of it, the same way
+//! embassy-sync's waitqueue sits below its higher-level synchronization types.
+
+use crate::ffi;
+use core::{convert::TryFrom, ptr, time::Duration};
+
+/// Why a call to [`wait_if_equal`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitOutcome {
+    /// The word at `addr` already didn't equal `expected` when the call was made, or a matching
+    /// [`wake`] released the caller. The word isn't read again after a wake.
+    ValueChanged,
+    /// The optional timeout elapsed before either of the above happened.
+    TimedOut,
+}
+
+/// Waits until the 32-bits word at `addr` no longer equals `expected`.
+///
+/// If it doesn't already, the calling task is parked until a [`wake`] call targeting `addr`
+/// wakes it up, or `timeout` elapses.
+///
+/// `addr` is read directly as a pointer into this program's own linear memory, without any
+/// check: it must be a valid, 4-bytes-aligned pointer, readable for as long as this future
+/// exists, or the read is undefined behaviour.
+pub async fn wait_if_equal(addr: u32, expected: u32, timeout: Option<Duration>) -> WaitOutcome {
+    // The handler behind this interface has no way to read the calling program's linear memory,
+    // so it can't perform the "doesn't already equal `expected`" check itself atomically with
+    // parking the way a real futex's `FUTEX_WAIT` does. `addr` is a pointer into *this* program's
+    // own memory, though, so the check can be done right here instead, before anything is even
+    // sent: nothing else runs on this cooperative, single-threaded executor between this read and
+    // the message emitted below, so as far as this task is concerned the two happen atomically,
+    // same as the kernel-side check it stands in for.
+    //
+    // Safety: see the requirements on `addr` documented above.
+    if unsafe { ptr::read_volatile(addr as *const u32) } != expected {
+        return WaitOutcome::ValueChanged;
+    }
+
+    let message = ffi::ThreadsMessage::WaitIfEqual(ffi::WaitIfEqual {
+        addr,
+        expected,
+        timeout: timeout.map(|d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)),
+    });
+
+    let timed_out: bool = redshirt_syscalls::emit_message_with_response(&ffi::INTERFACE, message)
+        .unwrap()
+        .await
+        .unwrap();
+
+    if timed_out {
+        WaitOutcome::TimedOut
+    } else {
+        WaitOutcome::ValueChanged
+    }
+}
+
+/// Wakes up to `count` tasks currently parked in [`wait_if_equal`] on `addr`. Pass
+/// `u32::max_value()` to wake all of them.
+pub fn wake(addr: u32, count: u32) {
+    let message = ffi::ThreadsMessage::Wake(ffi::Wake { addr, count });
+    redshirt_syscalls::emit_message_without_response(&ffi::INTERFACE, &message).unwrap();
+}
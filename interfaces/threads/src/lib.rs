@@ -20,7 +20,9 @@
 
 extern crate alloc;
 
+pub mod executor;
 pub mod ffi;
+pub mod futex;
 
 /// Creates a new thread, executing the function passed as parameter.
 ///
@@ -30,6 +32,9 @@ pub mod ffi;
 /// >              thread can exist at any given point in time. More specifically, LLVM assumes
 /// >              that only a single stack exists, and maintains a stack pointer as a global
 /// >              variable. It is therefore unsound to use stack variables on separate threads.
+///
+/// See the [`executor`] module for a sound alternative that provides real concurrency without
+/// requiring a second stack.
 pub unsafe fn spawn_thread(function: impl FnOnce()) {
     spawn_thread_inner(function)
 }
@@ -0,0 +1,73 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! FFI bindings for the `time` interface.
+//!
+//! SCOPE NOTE on [`TimeMessage::WaitMonotonicInterval`]: the request that introduced it asked
+//! for "a notification-style response path ... a stream of answers keyed by the original message
+//! id", i.e. one subscription message answered repeatedly. What's shipped instead answers each
+//! emission exactly once and hands back the next deadline for the caller to reissue with. That's
+//! a real change to the requested contract, not just an implementation detail — every interval
+//! consumer needs its own reissue loop instead of subscribing once — made because the core's
+//! message contract answers a given `MessageId` exactly once (every `NativeProgramRef` handler
+//! in this codebase treats a second answer to the same id as an `unreachable!()` bug) and has no
+//! repeating-notification primitive to build the originally requested stream on top of. Flagging
+//! this here since it's a scope reduction that should get sign-off from whoever filed the
+//! request, not something to treat as settled by virtue of being merged.
+
+use parity_scale_codec::{Decode, Encode};
+
+pub const INTERFACE: redshirt_syscalls::InterfaceHash = redshirt_syscalls::InterfaceHash::from_raw_hash([
+    0x33, 0x60, 0x74, 0x80, 0x5f, 0xc8, 0x53, 0x98, 0x7a, 0xbe, 0x6f, 0x7f, 0xe3, 0xad, 0x97, 0xa6,
+    0xa6, 0xf3, 0x07, 0x7a, 0x16, 0x39, 0x1f, 0xec, 0x74, 0x4f, 0x67, 0x1a, 0x01, 0x5f, 0xbd, 0x7e,
+]);
+
+/// Message destined to the `time` interface.
+#[derive(Debug, Encode, Decode)]
+pub enum TimeMessage {
+    /// Returns the current value of the monotonic clock, in nanoseconds. Answered with a `u128`.
+    GetMonotonic,
+    /// Waits until the monotonic clock reaches the given value, in nanoseconds. Answered
+    /// immediately if it already has. Answered with `()`.
+    WaitMonotonic(u128),
+    /// Waits for the monotonic clock to reach `first`, then answers, and is expected to be
+    /// re-emitted with `first` set to the returned [`IntervalTick::next_deadline`] to keep
+    /// ticking at `period` nanoseconds intervals.
+    ///
+    /// Each emission of this message is answered exactly once: a genuinely repeating
+    /// notification would need a message id that survives being answered more than once, which
+    /// the core doesn't provide, so the periodic behavior is instead built by the caller
+    /// reissuing the message with the absolute deadline the previous answer handed back. This
+    /// keeps ticks phase-aligned to `first` instead of drifting by the caller's own round-trip
+    /// latency.
+    WaitMonotonicInterval {
+        /// Absolute monotonic time, in nanoseconds, of the next tick.
+        first: u128,
+        /// Period between two ticks, in nanoseconds.
+        period: u128,
+    },
+}
+
+/// Answer to [`TimeMessage::WaitMonotonicInterval`].
+#[derive(Debug, Encode, Decode)]
+pub struct IntervalTick {
+    /// Number of periods that had already fully elapsed by the time this tick was answered,
+    /// beyond the one being delivered. Non-zero only if the caller was too slow to reissue the
+    /// message, or the handler itself fell behind.
+    pub missed_ticks: u64,
+    /// Absolute monotonic time, in nanoseconds, to pass as `first` when reissuing
+    /// [`TimeMessage::WaitMonotonicInterval`] to keep the interval going.
+    pub next_deadline: u128,
+}
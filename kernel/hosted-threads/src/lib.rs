@@ -0,0 +1,306 @@
+// Copyright (C) 2019-2020  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the synchronization half (`WaitIfEqual`/`Wake`) of the `threads` interface.
+//!
+//! `ThreadsMessage::New` is not handled here: spinning up a new execution context inside a
+//! running program is a virtual-machine concern, not something a native program sitting on the
+//! other side of a message-passing interface can do.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_hosted_time::TimingWheel;
+use redshirt_threads_interface::ffi as threads_ffi;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    pin::Pin,
+    sync::atomic,
+    time::Duration,
+};
+
+/// State machine for the `threads` interface's `WaitIfEqual`/`Wake` messages.
+pub struct ThreadsHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Accessed only by `next_event`.
+    inner: Mutex<ThreadsHandlerInner>,
+    /// Send on this channel the received interface messages.
+    messages_tx: mpsc::UnboundedSender<Event>,
+}
+
+/// Separate struct behind a mutex.
+struct ThreadsHandlerInner {
+    /// For every `(Pid, addr)` with at least one parked waiter, the message ids waiting on it,
+    /// in wake-up order.
+    ///
+    /// `addr` is a pointer into the *emitting program's own* linear memory, not a global
+    /// address: two unrelated processes routinely end up with the same `addr` value (e.g. both
+    /// have a lock word near the start of their data section), and without the `Pid` in the key
+    /// one process's `Wake` would release another's waiters.
+    wait_queues: HashMap<(Pid, u32), VecDeque<MessageId>>,
+    /// Reverse of `wait_queues`, so a fired or cancelled waiter can be pulled out of its queue
+    /// without knowing its `(Pid, addr)` up front.
+    waiter_addr: HashMap<MessageId, (Pid, u32)>,
+    /// For every program with at least one parked waiter, the set of message ids it owns. Used
+    /// to cancel everything at once when the program dies.
+    waiters_by_pid: HashMap<Pid, HashSet<MessageId>>,
+    /// Reverse of `waiters_by_pid`.
+    waiter_pid: HashMap<MessageId, Pid>,
+    /// Hashed timing wheel driving `WaitIfEqual` timeouts. The same machinery `hosted-time` uses
+    /// for `WaitMonotonic`, reused here instead of growing a second copy.
+    timeouts: TimingWheel,
+    /// Fires every time `timeouts` must advance by one slot.
+    next_wakeup: Pin<Box<dyn Future<Output = ()> + Send>>,
+    /// Entries that are ready to be turned into a [`NativeProgramEvent::Answer`], together with
+    /// the answer body to send back.
+    ready_to_answer: VecDeque<(MessageId, EncodedMessage)>,
+    /// Receiving side of [`ThreadsHandler::messages_tx`].
+    messages_rx: mpsc::UnboundedReceiver<Event>,
+}
+
+/// Everything that can be sent from the synchronous [`NativeProgramRef`] methods to the
+/// `next_event` loop.
+enum Event {
+    /// An interface message was received. Carries the emitter's message id, which is only
+    /// absent for the fire-and-forget `Wake` and `New` messages.
+    Interface(threads_ffi::ThreadsMessage, Option<MessageId>, Pid),
+    /// A program died; every waiter it owns must be cancelled.
+    ProcessDestroyed(Pid),
+}
+
+impl ThreadsHandlerInner {
+    /// Parks `message_id` (owned by `pid`) on `addr`, and schedules its timeout if one was
+    /// requested.
+    fn park(&mut self, pid: Pid, message_id: MessageId, addr: u32, timeout_nanos: Option<u64>) {
+        self.wait_queues
+            .entry((pid, addr))
+            .or_default()
+            .push_back(message_id);
+        self.waiter_addr.insert(message_id, (pid, addr));
+        self.waiters_by_pid.entry(pid).or_default().insert(message_id);
+        self.waiter_pid.insert(message_id, pid);
+
+        if let Some(timeout_nanos) = timeout_nanos {
+            // If the number of ticks is larger than a `u64`, we simply don't schedule a timeout.
+            // We assume that we will never reach this time ever.
+            if let Ok(ticks) =
+                u64::try_from(redshirt_hosted_time::ticks_from_now(u128::from(timeout_nanos)))
+            {
+                self.timeouts.schedule(message_id, ticks);
+            }
+        }
+    }
+
+    /// Removes `message_id` from whichever wait queue and bookkeeping it's in. Called once it
+    /// fires, for whatever reason (woken up, timed out, or its owner died).
+    fn remove_waiter(&mut self, message_id: MessageId) {
+        if let Some(key) = self.waiter_addr.remove(&message_id) {
+            if let Some(queue) = self.wait_queues.get_mut(&key) {
+                queue.retain(|id| *id != message_id);
+                if queue.is_empty() {
+                    self.wait_queues.remove(&key);
+                }
+            }
+        }
+        if let Some(pid) = self.waiter_pid.remove(&message_id) {
+            if let Some(owned) = self.waiters_by_pid.get_mut(&pid) {
+                owned.remove(&message_id);
+                if owned.is_empty() {
+                    self.waiters_by_pid.remove(&pid);
+                }
+            }
+        }
+        self.timeouts.cancel(message_id);
+    }
+
+    /// Releases up to `count` waiters belonging to `pid` and parked on `addr`, queueing their
+    /// wake-up answer. Waiters parked on the same `addr` value by a different process are left
+    /// untouched: `addr` is only meaningful within the process that emitted it.
+    fn wake(&mut self, pid: Pid, addr: u32, count: u32) {
+        let mut released = 0u32;
+        while released < count {
+            let message_id = match self
+                .wait_queues
+                .get_mut(&(pid, addr))
+                .and_then(VecDeque::pop_front)
+            {
+                Some(message_id) => message_id,
+                None => break,
+            };
+            self.remove_waiter(message_id);
+            self.ready_to_answer.push_back((message_id, false.encode()));
+            released += 1;
+        }
+    }
+
+    /// Cancels every waiter, fired or not, that belongs to `pid`.
+    fn cancel_pid(&mut self, pid: Pid) {
+        let message_ids = match self.waiters_by_pid.remove(&pid) {
+            Some(message_ids) => message_ids,
+            None => return,
+        };
+
+        for message_id in message_ids {
+            self.waiter_pid.remove(&message_id);
+            if let Some(key) = self.waiter_addr.remove(&message_id) {
+                if let Some(queue) = self.wait_queues.get_mut(&key) {
+                    queue.retain(|id| *id != message_id);
+                    if queue.is_empty() {
+                        self.wait_queues.remove(&key);
+                    }
+                }
+            }
+            self.timeouts.cancel(message_id);
+            self.ready_to_answer.retain(|(id, _)| *id != message_id);
+        }
+    }
+}
+
+impl ThreadsHandler {
+    /// Initializes the new state machine for the `threads` interface's synchronization half.
+    pub fn new() -> Self {
+        let (messages_tx, messages_rx) = mpsc::unbounded();
+        let next_wakeup = Box::pin(futures_timer::Delay::new(Duration::from_nanos(
+            u64::try_from(redshirt_hosted_time::tick_duration_nanos()).unwrap_or(u64::MAX),
+        )));
+
+        ThreadsHandler {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(ThreadsHandlerInner {
+                wait_queues: HashMap::new(),
+                waiter_addr: HashMap::new(),
+                waiters_by_pid: HashMap::new(),
+                waiter_pid: HashMap::new(),
+                timeouts: TimingWheel::new(),
+                next_wakeup,
+                ready_to_answer: VecDeque::new(),
+                messages_rx,
+            }),
+            messages_tx,
+        }
+    }
+}
+
+impl<'a> NativeProgramRef<'a> for &'a ThreadsHandler {
+    type Future =
+        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event(self) -> Self::Future {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        threads_ffi::INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let mut inner = self.inner.lock().await;
+            let inner = &mut *inner;
+
+            loop {
+                if let Some((message_id, answer)) = inner.ready_to_answer.pop_front() {
+                    return NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(answer),
+                    };
+                }
+
+                match future::select(&mut inner.next_wakeup, inner.messages_rx.next()).await {
+                    future::Either::Left((_, _)) => {
+                        inner.next_wakeup = Box::pin(futures_timer::Delay::new(Duration::from_nanos(
+                            u64::try_from(redshirt_hosted_time::tick_duration_nanos())
+                                .unwrap_or(u64::MAX),
+                        )));
+
+                        for message_id in inner.timeouts.advance() {
+                            inner.remove_waiter(message_id);
+                            inner.ready_to_answer.push_back((message_id, true.encode()));
+                        }
+                    }
+                    future::Either::Right((
+                        Some(Event::Interface(message, message_id, emitter_pid)),
+                        _,
+                    )) => match message {
+                        threads_ffi::ThreadsMessage::New(_) => {
+                            // Creating a thread needs a second execution context inside the
+                            // calling program, which only the virtual machine hosting it can set
+                            // up; there is nothing for this interface handler to do.
+                        }
+                        threads_ffi::ThreadsMessage::WaitIfEqual(wait) => {
+                            let message_id = match message_id {
+                                Some(message_id) => message_id,
+                                None => continue,
+                            };
+                            // No "does `wait.addr` still equal `wait.expected`?" check here: this
+                            // handler has no way to read the calling program's linear memory, so
+                            // it couldn't do that check atomically with parking anyway.
+                            // `redshirt_threads_interface::futex::wait_if_equal` already performs
+                            // it locally, where the memory is actually available, before this
+                            // message is ever sent — every `WaitIfEqual` that reaches this handler
+                            // is therefore known to still have matched `expected` an instant ago,
+                            // and parking unconditionally is correct.
+                            inner.park(emitter_pid, message_id, wait.addr, wait.timeout);
+                        }
+                        threads_ffi::ThreadsMessage::Wake(wake) => {
+                            inner.wake(emitter_pid, wake.addr, wake.count);
+                        }
+                    },
+                    future::Either::Right((Some(Event::ProcessDestroyed(pid)), _)) => {
+                        inner.cancel_pid(pid);
+                    }
+                    future::Either::Right((None, _)) => unreachable!(),
+                }
+            }
+        })
+    }
+
+    fn interface_message(
+        self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        if interface != threads_ffi::INTERFACE {
+            unreachable!()
+        }
+        if let Ok(msg) = threads_ffi::ThreadsMessage::decode(message) {
+            self.messages_tx
+                .unbounded_send(Event::Interface(msg, message_id, emitter_pid))
+                .unwrap();
+        }
+    }
+
+    fn process_destroyed(self, pid: Pid) {
+        // Cancellation itself happens in `next_event`, which is the sole owner of `inner`; we
+        // just notify it through the same channel interface messages already go through.
+        self.messages_tx
+            .unbounded_send(Event::ProcessDestroyed(pid))
+            .unwrap();
+    }
+
+    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -15,7 +15,7 @@
 
 //! Implements the time interface.
 
-use futures::{channel::mpsc, lock::Mutex, prelude::*, stream::FuturesUnordered};
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
 use futures_timer::Delay;
 use redshirt_core::native::{
     DummyMessageIdWrite, NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef,
@@ -24,30 +24,151 @@ use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, Mes
 use redshirt_system_time_interface::ffi as system_time_ffi;
 use redshirt_time_interface::ffi as time_ffi;
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     convert::TryFrom,
+    mem,
     pin::Pin,
     sync::atomic,
     time::{Duration, Instant, SystemTime},
 };
 
+/// Number of slots in the timing wheel.
+const WHEEL_SLOTS: u64 = 256;
+
+/// Duration of a single tick of the wheel. Every `WaitMonotonic` is rounded up to the next
+/// multiple of this duration, which bounds the wheel's resolution in exchange for O(1)
+/// scheduling.
+const TICK_DURATION: Duration = Duration::from_millis(10);
+
+/// Source of time and wakeups used by a [`TimerHandler`].
+///
+/// Abstracting over this lets a host plug in a clock other than `std::time` (a hardware
+/// counter, a deterministic clock for record/replay testing, a `wasm`-hosted timer, ...)
+/// without forking the handler, in the same spirit as embassy-time's `Driver`.
+pub trait TimeDriver: Send + Sync + 'static {
+    /// Returns the current value of the monotonic clock, in nanoseconds.
+    fn now_monotonic(&self) -> u128;
+
+    /// Returns the current value of the wall-clock, in nanoseconds since UNIX_EPOCH.
+    fn now_system(&self) -> u128;
+
+    /// Returns a future that resolves once [`TimeDriver::now_monotonic`] has reached or passed
+    /// `at`. The driver decides how it actually blocks until then.
+    fn schedule_wakeup(&self, at: u128) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// Default [`TimeDriver`], backed by `std::time::Instant` and `std::time::SystemTime`.
+pub struct StdTimeDriver {
+    /// Instant used as the origin of the monotonic clock.
+    start: Instant,
+}
+
+impl StdTimeDriver {
+    /// Initializes a new [`StdTimeDriver`].
+    pub fn new() -> Self {
+        StdTimeDriver {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for StdTimeDriver {
+    fn default() -> Self {
+        StdTimeDriver::new()
+    }
+}
+
+impl TimeDriver for StdTimeDriver {
+    fn now_monotonic(&self) -> u128 {
+        duration_to_u128(self.start.elapsed())
+    }
+
+    fn now_system(&self) -> u128 {
+        duration_to_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap(),
+        )
+    }
+
+    fn schedule_wakeup(&self, at: u128) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let dur_from_now = at.saturating_sub(self.now_monotonic());
+        let nanos = u64::try_from(dur_from_now).unwrap_or(u64::MAX);
+        Box::pin(Delay::new(Duration::from_nanos(nanos)))
+    }
+}
+
 /// State machine for `time` interface messages handling.
-pub struct TimerHandler {
+///
+/// Generic over the [`TimeDriver`] used to read the clocks and schedule wakeups; defaults to
+/// [`StdTimeDriver`].
+pub struct TimerHandler<D: TimeDriver = StdTimeDriver> {
     /// If true, we have sent the time interface registration message.
     time_registered: atomic::AtomicBool,
     /// If true, we have sent the system-time interface registration message.
     system_time_registered: atomic::AtomicBool,
+    /// Source of time and wakeups.
+    driver: D,
     /// Accessed only by `next_event`.
     inner: Mutex<TimerHandlerInner>,
     /// Send on this channel the received interface messages.
-    messages_tx: mpsc::UnboundedSender<(Message, MessageId)>,
+    messages_tx: mpsc::UnboundedSender<Event>,
 }
 
 /// Separate struct behind a mutex.
 struct TimerHandlerInner {
-    /// Stream of message IDs to answer.
-    timers: FuturesUnordered<Pin<Box<dyn Future<Output = MessageId> + Send>>>, // TODO: meh for boxing
+    /// Hashed timing wheel containing every pending `WaitMonotonic` and
+    /// `WaitMonotonicInterval`.
+    wheel: TimingWheel,
+    /// Fires every time the wheel must advance by one slot.
+    next_wakeup: Pin<Box<dyn Future<Output = ()> + Send>>,
+    /// Entries that expired but haven't been turned into a [`NativeProgramEvent::Answer`] yet,
+    /// together with the answer body to send back.
+    ready_to_answer: VecDeque<(MessageId, EncodedMessage)>,
+    /// Message ids scheduled through `WaitMonotonicInterval`, so that `next_event` can compute
+    /// their [`time_ffi::IntervalTick`] answer once they fire. Each entry is removed the moment
+    /// it fires: a `WaitMonotonicInterval` message is answered exactly once, same as every other
+    /// message, and it is up to the caller to reissue it to keep the interval going.
+    pending_intervals: HashMap<MessageId, PendingInterval>,
+    /// For every program with at least one pending timer, the set of message ids it is waiting
+    /// on. Used to cancel everything at once when the program dies.
+    timers_by_pid: HashMap<Pid, HashSet<MessageId>>,
+    /// Reverse of `timers_by_pid`, so that a fired or answered timer can be removed from its
+    /// owner's set without knowing the `Pid` up front.
+    timer_owner: HashMap<MessageId, Pid>,
     /// Receiving side of [`TimerHandler::messages_tx`].
-    messages_rx: mpsc::UnboundedReceiver<(Message, MessageId)>,
+    messages_rx: mpsc::UnboundedReceiver<Event>,
+}
+
+/// Bookkeeping for a `WaitMonotonicInterval` that hasn't fired yet.
+struct PendingInterval {
+    /// Absolute monotonic time, in nanoseconds, that was requested to fire at.
+    first: u128,
+    /// Period between two ticks, in nanoseconds.
+    period: u128,
+}
+
+/// An entry scheduled in the timing wheel.
+struct WheelEntry {
+    /// Message to answer once this entry's `rotations` reaches zero.
+    message_id: MessageId,
+    /// Number of full trips around the wheel left before this entry actually expires.
+    rotations: u64,
+}
+
+/// Hashed timing wheel. Turns inserting, cancelling and firing a timer into amortized O(1)
+/// operations, as opposed to polling one boxed future per timer.
+///
+/// Public so that other native programs with their own message-id-keyed timeouts (e.g.
+/// `hosted-threads`'s `WaitIfEqual` timeout) can reuse it instead of each growing their own copy.
+pub struct TimingWheel {
+    /// One bucket of pending entries per slot. Has a fixed length of [`WHEEL_SLOTS`].
+    slots: Vec<Vec<WheelEntry>>,
+    /// Slot that the wheel is currently parked on.
+    current_slot: u64,
+    /// For every still-pending message, the slot it was inserted in. Lets us cancel an entry
+    /// without scanning every slot.
+    locations: HashMap<MessageId, u64>,
 }
 
 enum Message {
@@ -55,26 +176,132 @@ enum Message {
     SystemTime(system_time_ffi::TimeMessage),
 }
 
-impl TimerHandler {
-    /// Initializes the new state machine for timers.
+/// Everything that can be sent from the synchronous [`NativeProgramRef`] methods to the
+/// `next_event` loop.
+enum Event {
+    /// An interface message was received and needs answering.
+    Interface(Message, MessageId, Pid),
+    /// A program died; every timer it was waiting on must be cancelled.
+    ProcessDestroyed(Pid),
+}
+
+impl TimingWheel {
     pub fn new() -> Self {
+        TimingWheel {
+            slots: (0..WHEEL_SLOTS).map(|_| Vec::new()).collect(),
+            current_slot: 0,
+            locations: HashMap::new(),
+        }
+    }
+
+    /// Schedules `message_id` to expire in `ticks_from_now` ticks.
+    ///
+    /// `ticks_from_now` is clamped to at least `1`: an entry scheduled into `current_slot`
+    /// itself (i.e. an already-due deadline) would only be visited a full revolution later,
+    /// since `advance` always moves past `current_slot` before looking at a slot's entries.
+    pub fn schedule(&mut self, message_id: MessageId, ticks_from_now: u64) {
+        let ticks_from_now = ticks_from_now.max(1);
+        let slot = (self.current_slot + ticks_from_now) % WHEEL_SLOTS;
+        // `- 1` because the slot's *first* visit after scheduling is the one at
+        // `ticks_from_now` itself, not one full revolution later: e.g. `ticks_from_now ==
+        // WHEEL_SLOTS` lands back on `current_slot`, and that very first revisit is the one
+        // that must fire, so it needs `rotations == 0` rather than `1`.
+        let rotations = (ticks_from_now - 1) / WHEEL_SLOTS;
+        self.slots[usize::try_from(slot).unwrap()].push(WheelEntry {
+            message_id,
+            rotations,
+        });
+        self.locations.insert(message_id, slot);
+    }
+
+    /// Removes a previously-scheduled entry. Does nothing if `message_id` is unknown, as it
+    /// might already have fired.
+    pub fn cancel(&mut self, message_id: MessageId) {
+        if let Some(slot) = self.locations.remove(&message_id) {
+            self.slots[usize::try_from(slot).unwrap()]
+                .retain(|entry| entry.message_id != message_id);
+        }
+    }
+
+    /// Advances the wheel by one slot, returning every message whose timer just expired.
+    pub fn advance(&mut self) -> Vec<MessageId> {
+        self.current_slot = (self.current_slot + 1) % WHEEL_SLOTS;
+        let slot_index = usize::try_from(self.current_slot).unwrap();
+        let entries = mem::take(&mut self.slots[slot_index]);
+
+        let mut fired = Vec::new();
+        for mut entry in entries {
+            if entry.rotations == 0 {
+                self.locations.remove(&entry.message_id);
+                fired.push(entry.message_id);
+            } else {
+                entry.rotations -= 1;
+                self.slots[slot_index].push(entry);
+            }
+        }
+        fired
+    }
+}
+
+impl TimerHandlerInner {
+    /// Registers `message_id` as belonging to `pid`, so that it gets cancelled if `pid` dies.
+    fn track(&mut self, pid: Pid, message_id: MessageId) {
+        self.timers_by_pid.entry(pid).or_default().insert(message_id);
+        self.timer_owner.insert(message_id, pid);
+    }
+
+    /// Forgets about `message_id`, once it has fired or been answered.
+    fn untrack(&mut self, message_id: MessageId) {
+        if let Some(pid) = self.timer_owner.remove(&message_id) {
+            if let Some(owned) = self.timers_by_pid.get_mut(&pid) {
+                owned.remove(&message_id);
+                if owned.is_empty() {
+                    self.timers_by_pid.remove(&pid);
+                }
+            }
+        }
+    }
+
+    /// Cancels every timer, fired or not, that belongs to `pid`.
+    fn cancel_pid(&mut self, pid: Pid) {
+        let message_ids = match self.timers_by_pid.remove(&pid) {
+            Some(message_ids) => message_ids,
+            None => return,
+        };
+
+        for message_id in message_ids {
+            self.timer_owner.remove(&message_id);
+            self.wheel.cancel(message_id);
+            self.pending_intervals.remove(&message_id);
+            self.ready_to_answer.retain(|(id, _)| *id != message_id);
+        }
+    }
+}
+
+impl TimerHandler<StdTimeDriver> {
+    /// Initializes the new state machine for timers, using the default [`StdTimeDriver`].
+    pub fn new() -> Self {
+        TimerHandler::with_driver(StdTimeDriver::new())
+    }
+}
+
+impl<D: TimeDriver> TimerHandler<D> {
+    /// Initializes the new state machine for timers, sourcing time and wakeups from `driver`.
+    pub fn with_driver(driver: D) -> Self {
         let (messages_tx, messages_rx) = mpsc::unbounded();
+        let next_wakeup = driver.schedule_wakeup(driver.now_monotonic() + tick_duration_nanos());
 
         TimerHandler {
             time_registered: atomic::AtomicBool::new(false),
             system_time_registered: atomic::AtomicBool::new(false),
+            driver,
             inner: Mutex::new(TimerHandlerInner {
-                timers: {
-                    let timers =
-                        FuturesUnordered::<Pin<Box<dyn Future<Output = MessageId> + Send>>>::new();
-                    // TODO: ugh; pushing a never-ending future, otherwise we get a permanent `None` when polling
-                    timers.push(Box::pin(async move {
-                        loop {
-                            futures::pending!()
-                        }
-                    }));
-                    timers
-                },
+                wheel: TimingWheel::new(),
+                next_wakeup,
+                ready_to_answer: VecDeque::new(),
+                pending_intervals: HashMap::new(),
+                timers_by_pid: HashMap::new(),
+                timer_owner: HashMap::new(),
                 messages_rx,
             }),
             messages_tx,
@@ -82,7 +309,7 @@ impl TimerHandler {
     }
 }
 
-impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
+impl<'a, D: TimeDriver> NativeProgramRef<'a> for &'a TimerHandler<D> {
     type Future =
         Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
     type MessageIdWrite = DummyMessageIdWrite;
@@ -118,23 +345,43 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
             let inner = &mut *inner;
 
             loop {
-                match future::select(inner.timers.next(), inner.messages_rx.next()).await {
-                    future::Either::Left((Some(message_id), _)) => {
-                        return NativeProgramEvent::Answer {
-                            message_id,
-                            answer: Ok(().encode()),
-                        };
+                if let Some((message_id, answer)) = inner.ready_to_answer.pop_front() {
+                    return NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(answer),
+                    };
+                }
+
+                match future::select(&mut inner.next_wakeup, inner.messages_rx.next()).await {
+                    future::Either::Left((_, _)) => {
+                        let now = self.driver.now_monotonic();
+                        inner.next_wakeup =
+                            self.driver.schedule_wakeup(now + tick_duration_nanos());
+
+                        for message_id in inner.wheel.advance() {
+                            inner.untrack(message_id);
+                            if let Some(pending) = inner.pending_intervals.remove(&message_id) {
+                                let tick = interval_tick(pending.first, pending.period, now);
+                                inner.ready_to_answer.push_back((message_id, tick.encode()));
+                            } else {
+                                inner.ready_to_answer.push_back((message_id, ().encode()));
+                            }
+                        }
                     }
-                    future::Either::Right((Some((Message::Time(time_message), message_id)), _)) => {
+                    future::Either::Right((
+                        Some(Event::Interface(Message::Time(time_message), message_id, emitter_pid)),
+                        _,
+                    )) => {
                         match time_message {
                             time_ffi::TimeMessage::GetMonotonic => {
                                 return NativeProgramEvent::Answer {
                                     message_id,
-                                    answer: Ok(monotonic_clock().encode()),
+                                    answer: Ok(self.driver.now_monotonic().encode()),
                                 };
                             }
                             time_ffi::TimeMessage::WaitMonotonic(until) => {
-                                match until.checked_sub(monotonic_clock()) {
+                                let now = self.driver.now_monotonic();
+                                match until.checked_sub(now).filter(|d| *d != 0) {
                                     None => {
                                         return NativeProgramEvent::Answer {
                                             message_id,
@@ -142,32 +389,54 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
                                         }
                                     }
                                     Some(dur_from_now) => {
-                                        // If `dur_from_now` is larger than a `u64`, we simply don't insert any timer.
-                                        // We assume that we will never reach this time ever.
-                                        if let Ok(dur) = u64::try_from(dur_from_now) {
-                                            let delay = Delay::new(Duration::from_nanos(dur));
-                                            inner.timers.push(Box::pin(async move {
-                                                delay.await;
-                                                message_id
-                                            }));
+                                        // If the number of ticks is larger than a `u64`, we
+                                        // simply don't insert any timer. We assume that we will
+                                        // never reach this time ever.
+                                        if let Ok(ticks) = u64::try_from(ticks_from_now(dur_from_now)) {
+                                            inner.wheel.schedule(message_id, ticks);
+                                            inner.track(emitter_pid, message_id);
                                         }
                                     }
                                 }
                             }
+                            time_ffi::TimeMessage::WaitMonotonicInterval { first, period } => {
+                                let now = self.driver.now_monotonic();
+                                if first <= now {
+                                    // Already due: answer straight away instead of round-tripping
+                                    // through the wheel. `message_id` is answered exactly once
+                                    // either way, so there is no repeated-`Answer` hazard here.
+                                    return NativeProgramEvent::Answer {
+                                        message_id,
+                                        answer: Ok(interval_tick(first, period, now).encode()),
+                                    };
+                                }
+
+                                if let Ok(ticks) =
+                                    u64::try_from(ticks_from_now(first.saturating_sub(now)))
+                                {
+                                    inner.wheel.schedule(message_id, ticks);
+                                    inner
+                                        .pending_intervals
+                                        .insert(message_id, PendingInterval { first, period });
+                                    inner.track(emitter_pid, message_id);
+                                }
+                            }
                         }
                     }
                     future::Either::Right((
-                        Some((Message::SystemTime(time_message), message_id)),
+                        Some(Event::Interface(Message::SystemTime(time_message), message_id, _)),
                         _,
                     )) => match time_message {
                         system_time_ffi::TimeMessage::GetSystem => {
                             return NativeProgramEvent::Answer {
                                 message_id,
-                                answer: Ok(system_clock().encode()),
+                                answer: Ok(self.driver.now_system().encode()),
                             };
                         }
                     },
-                    future::Either::Left((None, _)) => unreachable!(),
+                    future::Either::Right((Some(Event::ProcessDestroyed(pid)), _)) => {
+                        inner.cancel_pid(pid);
+                    }
                     future::Either::Right((None, _)) => unreachable!(),
                 }
             }
@@ -185,7 +454,11 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
             match time_ffi::TimeMessage::decode(message) {
                 Ok(msg) => {
                     self.messages_tx
-                        .unbounded_send((Message::Time(msg), message_id.unwrap()))
+                        .unbounded_send(Event::Interface(
+                            Message::Time(msg),
+                            message_id.unwrap(),
+                            emitter_pid,
+                        ))
                         .unwrap();
                 }
                 Err(_) => {}
@@ -194,7 +467,11 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
             match system_time_ffi::TimeMessage::decode(message) {
                 Ok(msg) => {
                     self.messages_tx
-                        .unbounded_send((Message::SystemTime(msg), message_id.unwrap()))
+                        .unbounded_send(Event::Interface(
+                            Message::SystemTime(msg),
+                            message_id.unwrap(),
+                            emitter_pid,
+                        ))
                         .unwrap();
                 }
                 Err(_) => {}
@@ -204,29 +481,124 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
         }
     }
 
-    fn process_destroyed(self, _: Pid) {}
+    fn process_destroyed(self, pid: Pid) {
+        // Cancellation itself happens in `next_event`, which is the sole owner of `inner`; we
+        // just notify it through the same channel interface messages already go through.
+        self.messages_tx
+            .unbounded_send(Event::ProcessDestroyed(pid))
+            .unwrap();
+    }
 
     fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
         unreachable!()
     }
 }
 
-fn monotonic_clock() -> u128 {
-    lazy_static::lazy_static! {
-        static ref CLOCK_START: Instant = Instant::now();
-    }
-    let start = *CLOCK_START;
-    duration_to_u128(start.elapsed())
+fn duration_to_u128(duration: Duration) -> u128 {
+    u128::from(duration.as_secs() * 1_000_000_000) + u128::from(duration.subsec_nanos())
 }
 
-fn system_clock() -> u128 {
-    duration_to_u128(
-        SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap(),
-    )
+/// Duration of one wheel tick, in nanoseconds.
+pub fn tick_duration_nanos() -> u128 {
+    TICK_DURATION.as_nanos()
 }
 
-fn duration_to_u128(duration: Duration) -> u128 {
-    u128::from(duration.as_secs() * 1_000_000_000) + u128::from(duration.subsec_nanos())
+/// Converts a duration expressed in nanoseconds into a number of wheel ticks, rounded up so
+/// that a timer never fires earlier than requested.
+///
+/// One extra tick is added on top of the rounded-up count, because the wheel's next advance can
+/// be less than a full tick away from now.
+pub fn ticks_from_now(nanos_from_now: u128) -> u128 {
+    let tick_nanos = tick_duration_nanos();
+    (nanos_from_now + tick_nanos - 1) / tick_nanos + 1
+}
+
+/// Builds the [`time_ffi::IntervalTick`] answer for a `WaitMonotonicInterval { first, period }`
+/// that is due, coalescing any ticks that were missed (e.g. because the handler was busy, or the
+/// deadline had already been due for a while when the message was received) into `missed_ticks`.
+fn interval_tick(first: u128, period: u128, now: u128) -> time_ffi::IntervalTick {
+    debug_assert!(first <= now);
+    // A zero period can't be coalesced against; treat it as a one-off tick with no catch-up.
+    let missed_ticks = if period == 0 {
+        0
+    } else {
+        u64::try_from((now - first) / period).unwrap_or(u64::MAX)
+    };
+    time_ffi::IntervalTick {
+        missed_ticks,
+        next_deadline: first + (u128::from(missed_ticks) + 1) * period.max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+
+    /// Advances `wheel` `count` times, asserting none of them fire anything.
+    fn advance_empty(wheel: &mut TimingWheel, count: u64) {
+        for _ in 0..count {
+            assert_eq!(wheel.advance(), Vec::new());
+        }
+    }
+
+    #[test]
+    fn schedule_fires_on_exact_multiple_of_wheel_slots() {
+        let mut wheel = TimingWheel::new();
+        let id = MessageId::from(1);
+        wheel.schedule(id, WHEEL_SLOTS);
+        advance_empty(&mut wheel, WHEEL_SLOTS - 1);
+        assert_eq!(wheel.advance(), vec![id]);
+    }
+
+    #[test]
+    fn schedule_fires_one_tick_past_a_full_revolution() {
+        let mut wheel = TimingWheel::new();
+        let id = MessageId::from(2);
+        wheel.schedule(id, WHEEL_SLOTS + 1);
+        advance_empty(&mut wheel, WHEEL_SLOTS);
+        assert_eq!(wheel.advance(), vec![id]);
+    }
+
+    #[test]
+    fn schedule_fires_after_two_full_revolutions() {
+        let mut wheel = TimingWheel::new();
+        let id = MessageId::from(3);
+        wheel.schedule(id, 2 * WHEEL_SLOTS);
+        advance_empty(&mut wheel, 2 * WHEEL_SLOTS - 1);
+        assert_eq!(wheel.advance(), vec![id]);
+    }
+
+    #[test]
+    fn cancel_removes_a_pending_entry_before_it_fires() {
+        let mut wheel = TimingWheel::new();
+        let id = MessageId::from(4);
+        wheel.schedule(id, 5);
+        wheel.cancel(id);
+        advance_empty(&mut wheel, 2 * WHEEL_SLOTS);
+    }
+
+    #[test]
+    fn cancel_pid_removes_a_pending_timer_before_it_fires() {
+        let (_messages_tx, messages_rx) = mpsc::unbounded();
+        let mut inner = TimerHandlerInner {
+            wheel: TimingWheel::new(),
+            next_wakeup: Box::pin(future::pending()),
+            ready_to_answer: VecDeque::new(),
+            pending_intervals: HashMap::new(),
+            timers_by_pid: HashMap::new(),
+            timer_owner: HashMap::new(),
+            messages_rx,
+        };
+
+        let pid = Pid::from(1);
+        let id = MessageId::from(5);
+        inner.track(pid, id);
+        inner.wheel.schedule(id, 5);
+
+        inner.cancel_pid(pid);
+
+        advance_empty(&mut inner.wheel, 2 * WHEEL_SLOTS);
+        assert!(!inner.timers_by_pid.contains_key(&pid));
+    }
 }